@@ -59,14 +59,40 @@ extern crate serde;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "std")]
+use core::panic::Location;
+#[cfg(feature = "std")]
+use core::sync::atomic::AtomicPtr;
+
 /// A threadsafe analogue to RefCell.
 pub struct AtomicRefCell<T: ?Sized> {
     borrow: AtomicUsize,
+    /// The location of the borrow that currently holds `borrow`, if any.
+    /// Only tracked when the `std` feature is enabled.
+    #[cfg(feature = "std")]
+    location: AtomicPtr<Location<'static>>,
     value: UnsafeCell<T>,
 }
 
+#[cfg(feature = "std")]
+impl<T: ?Sized> AtomicRefCell<T> {
+    #[inline]
+    fn held_location(&self) -> Option<&'static Location<'static>> {
+        // SAFETY: Every pointer ever stored here came from `Location::caller()`,
+        // which is `&'static`, so it's always either null or valid to dereference.
+        unsafe { self.location.load(atomic::Ordering::Acquire).as_ref() }
+    }
+}
+
 /// An error returned by [`AtomicRefCell::try_borrow`](struct.AtomicRefCell.html#method.try_borrow).
 pub struct BorrowError {
+    #[cfg(feature = "std")]
+    type_name: &'static str,
+    #[cfg(feature = "std")]
+    location: Option<&'static Location<'static>>,
+    #[cfg(not(feature = "std"))]
     _private: (),
 }
 
@@ -76,14 +102,37 @@ impl Debug for BorrowError {
     }
 }
 
+#[cfg(not(feature = "std"))]
 impl Display for BorrowError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         Display::fmt("already mutably borrowed", f)
     }
 }
 
+#[cfg(feature = "std")]
+impl Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(
+                f,
+                "already mutably borrowed: the conflicting borrow of `{}` was taken at {}",
+                self.type_name, location
+            ),
+            None => Display::fmt("already mutably borrowed", f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BorrowError {}
+
 /// An error returned by [`AtomicRefCell::try_borrow_mut`](struct.AtomicRefCell.html#method.try_borrow_mut).
 pub struct BorrowMutError {
+    #[cfg(feature = "std")]
+    type_name: &'static str,
+    #[cfg(feature = "std")]
+    location: Option<&'static Location<'static>>,
+    #[cfg(not(feature = "std"))]
     _private: (),
 }
 
@@ -93,18 +142,38 @@ impl Debug for BorrowMutError {
     }
 }
 
+#[cfg(not(feature = "std"))]
 impl Display for BorrowMutError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         Display::fmt("already borrowed", f)
     }
 }
 
+#[cfg(feature = "std")]
+impl Display for BorrowMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(
+                f,
+                "already borrowed: the conflicting borrow of `{}` was taken at {}",
+                self.type_name, location
+            ),
+            None => Display::fmt("already borrowed", f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BorrowMutError {}
+
 impl<T> AtomicRefCell<T> {
     /// Creates a new `AtomicRefCell` containing `value`.
     #[inline]
     pub const fn new(value: T) -> AtomicRefCell<T> {
         AtomicRefCell {
             borrow: AtomicUsize::new(0),
+            #[cfg(feature = "std")]
+            location: AtomicPtr::new(core::ptr::null_mut()),
             value: UnsafeCell::new(value),
         }
     }
@@ -115,58 +184,159 @@ impl<T> AtomicRefCell<T> {
         debug_assert!(self.borrow.load(atomic::Ordering::Acquire) == 0);
         self.value.into_inner()
     }
+
+    /// Replaces the wrapped value with a new one, returning the old value,
+    /// without deinitializing either one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    #[inline]
+    pub fn replace(&self, t: T) -> T {
+        core::mem::replace(&mut *self.borrow_mut(), t)
+    }
+
+    /// Replaces the wrapped value with a new one computed from `f`, returning
+    /// the old value, without deinitializing either one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    #[inline]
+    pub fn replace_with<F: FnOnce(&mut T) -> T>(&self, f: F) -> T {
+        let mut_borrow = &mut *self.borrow_mut();
+        let replacement = f(mut_borrow);
+        core::mem::replace(mut_borrow, replacement)
+    }
+
+    /// Takes the wrapped value, leaving `Default::default()` in its place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    #[inline]
+    pub fn take(&self) -> T
+    where
+        T: Default,
+    {
+        self.replace(Default::default())
+    }
+
+    /// Swaps the wrapped value of `self` with the wrapped value of `other`,
+    /// without deinitializing either one.
+    ///
+    /// This is a no-op if `self` and `other` point to the same `AtomicRefCell`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value in either `AtomicRefCell` is currently borrowed.
+    #[inline]
+    pub fn swap(&self, other: &Self) {
+        if !core::ptr::eq(self, other) {
+            core::mem::swap(&mut *self.borrow_mut(), &mut *other.borrow_mut());
+        }
+    }
 }
 
 impl<T: ?Sized> AtomicRefCell<T> {
+    /// Bundles the bits of state a borrow needs to acquire and (under `std`)
+    /// record its call site, so the borrow-path methods below don't need to
+    /// be forked into separate `std`/`not(std)` copies.
+    #[inline]
+    fn borrow_source(&self) -> BorrowSource<'_> {
+        BorrowSource {
+            borrow: &self.borrow,
+            #[cfg(feature = "std")]
+            location: &self.location,
+        }
+    }
+
+    /// Panics with a message describing a failed borrow, augmented (under
+    /// `std`) with the type name and call site of the conflicting borrow.
+    #[cold]
+    #[inline(never)]
+    fn panic_borrow_conflict(&self, s: &'static str) -> ! {
+        #[cfg(feature = "std")]
+        match self.held_location() {
+            Some(location) => panic!(
+                "{}: the conflicting borrow of `{}` was taken at {}",
+                s,
+                core::any::type_name::<T>(),
+                location
+            ),
+            None => panic!("{}", s),
+        }
+        #[cfg(not(feature = "std"))]
+        panic!("{}", s)
+    }
+
     /// Immutably borrows the wrapped value.
     #[inline]
+    #[cfg_attr(feature = "std", track_caller)]
     pub fn borrow(&self) -> AtomicRef<T> {
-        match AtomicBorrowRef::try_new(&self.borrow) {
+        match AtomicBorrowRef::try_new(self.borrow_source()) {
             Ok(borrow) => AtomicRef {
                 value: unsafe { NonNull::new_unchecked(self.value.get()) },
                 borrow,
             },
-            Err(s) => panic!("{}", s),
+            Err(s) => self.panic_borrow_conflict(s),
         }
     }
 
     /// Attempts to immutably borrow the wrapped value, but instead of panicking
     /// on a failed borrow, returns `Err`.
     #[inline]
+    #[cfg_attr(feature = "std", track_caller)]
     pub fn try_borrow(&self) -> Result<AtomicRef<T>, BorrowError> {
-        match AtomicBorrowRef::try_new(&self.borrow) {
+        match AtomicBorrowRef::try_new(self.borrow_source()) {
             Ok(borrow) => Ok(AtomicRef {
                 value: unsafe { NonNull::new_unchecked(self.value.get()) },
                 borrow,
             }),
-            Err(_) => Err(BorrowError { _private: () }),
+            Err(_) => Err(BorrowError {
+                #[cfg(feature = "std")]
+                type_name: core::any::type_name::<T>(),
+                #[cfg(feature = "std")]
+                location: self.held_location(),
+                #[cfg(not(feature = "std"))]
+                _private: (),
+            }),
         }
     }
 
     /// Mutably borrows the wrapped value.
     #[inline]
+    #[cfg_attr(feature = "std", track_caller)]
     pub fn borrow_mut(&self) -> AtomicRefMut<T> {
-        match AtomicBorrowRefMut::try_new(&self.borrow) {
+        match AtomicBorrowRefMut::try_new(self.borrow_source()) {
             Ok(borrow) => AtomicRefMut {
                 value: unsafe { NonNull::new_unchecked(self.value.get()) },
                 borrow,
                 marker: PhantomData,
             },
-            Err(s) => panic!("{}", s),
+            Err(s) => self.panic_borrow_conflict(s),
         }
     }
 
     /// Attempts to mutably borrow the wrapped value, but instead of panicking
     /// on a failed borrow, returns `Err`.
     #[inline]
+    #[cfg_attr(feature = "std", track_caller)]
     pub fn try_borrow_mut(&self) -> Result<AtomicRefMut<T>, BorrowMutError> {
-        match AtomicBorrowRefMut::try_new(&self.borrow) {
+        match AtomicBorrowRefMut::try_new(self.borrow_source()) {
             Ok(borrow) => Ok(AtomicRefMut {
                 value: unsafe { NonNull::new_unchecked(self.value.get()) },
                 borrow,
                 marker: PhantomData,
             }),
-            Err(_) => Err(BorrowMutError { _private: () }),
+            Err(_) => Err(BorrowMutError {
+                #[cfg(feature = "std")]
+                type_name: core::any::type_name::<T>(),
+                #[cfg(feature = "std")]
+                location: self.held_location(),
+                #[cfg(not(feature = "std"))]
+                _private: (),
+            }),
         }
     }
 
@@ -195,16 +365,52 @@ impl<T: ?Sized> AtomicRefCell<T> {
 //
 
 const HIGH_BIT: usize = !(::core::usize::MAX >> 1);
-const MAX_FAILED_BORROWS: usize = HIGH_BIT + (HIGH_BIT >> 1);
+
+// A mutable borrow is represented by `HIGH_BIT` plus a small count
+// of how many live guards currently share it (1 for a plain `borrow_mut`, or
+// more once `map_split` has split it further). The count occupies the few
+// bits immediately below `HIGH_BIT`, leaving the rest of the lower half
+// available for the immutable-borrow overflow detection below, which must
+// stay well clear of this field.
+const GUARD_COUNT_BITS: u32 = 4;
+const GUARD_COUNT_SHIFT: u32 = usize::BITS - 1 - GUARD_COUNT_BITS;
+const GUARD_COUNT_ONE: usize = 1 << GUARD_COUNT_SHIFT;
+const GUARD_COUNT_MASK: usize = ((1 << GUARD_COUNT_BITS) - 1) << GUARD_COUNT_SHIFT;
+const MAX_GUARD_COUNT: usize = (1 << GUARD_COUNT_BITS) - 1;
+
+// A stray immutable `fetch_add(1)` against an already-mutably-borrowed cell
+// only ever touches the bits below `GUARD_COUNT_SHIFT` (the real guard count,
+// held in `GUARD_COUNT_MASK`, is never itself incremented by a failed
+// immutable borrow). So overflow must be detected by looking at just that
+// stray sub-field, independent of how many live guards the mutable borrow
+// currently has: comparing against a constant that bakes in `HIGH_BIT` and
+// `GUARD_COUNT_MASK` would only catch the corruption once it happens to line
+// up with the worst-case (fully split) guard count, letting a stray
+// increment silently carry into the guard-count field and permanently wedge
+// the cell as "already mutably borrowed" in the far more common case of a
+// single, unsplit `borrow_mut`.
+const MAX_FAILED_BORROWS: usize = GUARD_COUNT_ONE >> 1;
+
+/// Bundles the atomic refcount with (under `std`) the slot used to record
+/// the call site of whichever borrow currently holds it, so that acquiring
+/// a borrow doesn't need a separate `std`/`not(std)` copy of every method.
+struct BorrowSource<'b> {
+    borrow: &'b AtomicUsize,
+    #[cfg(feature = "std")]
+    location: &'b AtomicPtr<Location<'static>>,
+}
 
 struct AtomicBorrowRef<'b> {
     borrow: &'b AtomicUsize,
+    #[cfg(feature = "std")]
+    location: &'b AtomicPtr<Location<'static>>,
 }
 
 impl<'b> AtomicBorrowRef<'b> {
     #[inline]
-    fn try_new(borrow: &'b AtomicUsize) -> Result<Self, &'static str> {
-        let new = borrow.fetch_add(1, atomic::Ordering::Acquire) + 1;
+    #[cfg_attr(feature = "std", track_caller)]
+    fn try_new(source: BorrowSource<'b>) -> Result<Self, &'static str> {
+        let new = source.borrow.fetch_add(1, atomic::Ordering::Acquire) + 1;
         if new & HIGH_BIT != 0 {
             // If the new count has the high bit set, that almost certainly
             // means there's an pre-existing mutable borrow. In that case,
@@ -215,13 +421,33 @@ impl<'b> AtomicBorrowRef<'b> {
             // The overflow check here ensures that an unbounded number of
             // immutable borrows during the scope of one mutable borrow
             // will soundly trigger a panic (or abort) rather than UB.
-            Self::check_overflow(borrow, new);
+            Self::check_overflow(source.borrow, new);
             Err("already mutably borrowed")
         } else {
-            Ok(AtomicBorrowRef { borrow: borrow })
+            #[cfg(feature = "std")]
+            source.location.store(
+                Location::caller() as *const Location<'static> as *mut _,
+                atomic::Ordering::Release,
+            );
+            Ok(AtomicBorrowRef {
+                borrow: source.borrow,
+                #[cfg(feature = "std")]
+                location: source.location,
+            })
         }
     }
 
+    #[inline]
+    fn source(&self) -> BorrowSource<'b> {
+        BorrowSource {
+            borrow: self.borrow,
+            #[cfg(feature = "std")]
+            location: self.location,
+        }
+    }
+}
+
+impl<'b> AtomicBorrowRef<'b> {
     #[cold]
     #[inline(never)]
     fn check_overflow(borrow: &'b AtomicUsize, new: usize) {
@@ -234,7 +460,7 @@ impl<'b> AtomicBorrowRef<'b> {
             // in a tight loop.
             borrow.fetch_sub(1, atomic::Ordering::Release);
             panic!("too many immutable borrows");
-        } else if new >= MAX_FAILED_BORROWS {
+        } else if (new & !(HIGH_BIT | GUARD_COUNT_MASK)) >= MAX_FAILED_BORROWS {
             // During the mutable borrow, an absurd number of threads have
             // attempted to increment the refcount with immutable borrows.
             // To avoid hypothetically wrapping the refcount, we abort the
@@ -276,28 +502,47 @@ impl<'b> Drop for AtomicBorrowRef<'b> {
         // the refcount before it fixes it up (and panics). But that never will
         // never happen in a real program, and this is a debug_assert! anyway.
         debug_assert!(old & HIGH_BIT == 0);
+        #[cfg(feature = "std")]
+        if old == 1 {
+            // We were the last immutable borrow; nothing else can be
+            // pointing at this location anymore.
+            self.location
+                .store(core::ptr::null_mut(), atomic::Ordering::Release);
+        }
     }
 }
 
 struct AtomicBorrowRefMut<'b> {
     borrow: &'b AtomicUsize,
+    #[cfg(feature = "std")]
+    location: &'b AtomicPtr<Location<'static>>,
 }
 
 impl<'b> Drop for AtomicBorrowRefMut<'b> {
     #[inline]
     fn drop(&mut self) {
-        self.borrow.store(0, atomic::Ordering::Release);
+        let old = self.borrow.fetch_sub(GUARD_COUNT_ONE, atomic::Ordering::Release);
+        // Only the last of the (possibly several, via `map_split`) guards
+        // sharing this mutable borrow clears the refcount. This also clears
+        // any stray immutable increments left behind by panicked threads.
+        if old & GUARD_COUNT_MASK == GUARD_COUNT_ONE {
+            #[cfg(feature = "std")]
+            self.location
+                .store(core::ptr::null_mut(), atomic::Ordering::Release);
+            self.borrow.store(0, atomic::Ordering::Release);
+        }
     }
 }
 
 impl<'b> AtomicBorrowRefMut<'b> {
     #[inline]
-    fn try_new(borrow: &'b AtomicUsize) -> Result<AtomicBorrowRefMut<'b>, &'static str> {
+    #[cfg_attr(feature = "std", track_caller)]
+    fn try_new(source: BorrowSource<'b>) -> Result<AtomicBorrowRefMut<'b>, &'static str> {
         // Use compare-and-swap to avoid corrupting the immutable borrow count
         // on illegal mutable borrows.
-        let old = match borrow.compare_exchange(
+        let old = match source.borrow.compare_exchange(
             0,
-            HIGH_BIT,
+            HIGH_BIT | GUARD_COUNT_ONE,
             atomic::Ordering::Acquire,
             atomic::Ordering::Relaxed,
         ) {
@@ -306,13 +551,63 @@ impl<'b> AtomicBorrowRefMut<'b> {
         };
 
         if old == 0 {
-            Ok(AtomicBorrowRefMut { borrow })
+            #[cfg(feature = "std")]
+            source.location.store(
+                Location::caller() as *const Location<'static> as *mut _,
+                atomic::Ordering::Release,
+            );
+            Ok(AtomicBorrowRefMut {
+                borrow: source.borrow,
+                #[cfg(feature = "std")]
+                location: source.location,
+            })
         } else if old & HIGH_BIT == 0 {
             Err("already immutably borrowed")
         } else {
             Err("already mutably borrowed")
         }
     }
+
+    /// Creates an additional guard sharing this mutable borrow, for use by
+    /// `map_split`. Unlike `AtomicBorrowRef`, this can't be a public `Clone`
+    /// impl, since an unconstrained way to duplicate a mutable borrow would
+    /// be unsound.
+    #[inline]
+    fn clone(&self) -> AtomicBorrowRefMut<'b> {
+        let new = self.borrow.fetch_add(GUARD_COUNT_ONE, atomic::Ordering::Acquire) + GUARD_COUNT_ONE;
+        Self::check_overflow(self.borrow, new);
+        AtomicBorrowRefMut {
+            borrow: self.borrow,
+            #[cfg(feature = "std")]
+            location: self.location,
+        }
+    }
+}
+
+impl<'b> AtomicBorrowRefMut<'b> {
+    #[cold]
+    #[inline(never)]
+    fn check_overflow(borrow: &'b AtomicUsize, new: usize) {
+        if (new & GUARD_COUNT_MASK) >> GUARD_COUNT_SHIFT >= MAX_GUARD_COUNT {
+            // We're one split away from overflowing the small guard-count
+            // field into `HIGH_BIT` itself, which would silently release the
+            // mutable borrow out from under any still-live guards. Undo the
+            // increment and abort rather than risk that.
+            //
+            // With `GUARD_COUNT_BITS` at 4, this fires as soon as a split
+            // would create a 15th live guard, so at most 14 guards can
+            // share one mutable borrow.
+            borrow.fetch_sub(GUARD_COUNT_ONE, atomic::Ordering::Release);
+            struct ForceAbort;
+            impl Drop for ForceAbort {
+                fn drop(&mut self) {
+                    panic!("Aborting to avoid unsound state of AtomicRefCell");
+                }
+            }
+            let _abort = ForceAbort;
+            panic!("Too many split mutable borrows");
+        }
+    }
 }
 
 unsafe impl<T: ?Sized + Send> Send for AtomicRefCell<T> {}
@@ -368,8 +663,9 @@ impl<T> From<T> for AtomicRefCell<T> {
 
 impl<'b> Clone for AtomicBorrowRef<'b> {
     #[inline]
+    #[cfg_attr(feature = "std", track_caller)]
     fn clone(&self) -> AtomicBorrowRef<'b> {
-        AtomicBorrowRef::try_new(self.borrow).unwrap()
+        AtomicBorrowRef::try_new(self.source()).unwrap()
     }
 }
 
@@ -427,6 +723,49 @@ impl<'b, T: ?Sized> AtomicRef<'b, T> {
             borrow: orig.borrow,
         })
     }
+
+    /// Splits an `AtomicRef` into two `AtomicRef`s for different components
+    /// of the borrowed data.
+    #[inline]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        orig: AtomicRef<'b, T>,
+        f: F,
+    ) -> (AtomicRef<'b, U>, AtomicRef<'b, V>)
+    where
+        F: FnOnce(&T) -> (&U, &V),
+    {
+        let (a, b) = f(&*orig);
+        let (a, b) = (NonNull::from(a), NonNull::from(b));
+        let borrow_b = orig.borrow.clone();
+        (
+            AtomicRef {
+                value: a,
+                borrow: orig.borrow,
+            },
+            AtomicRef {
+                value: b,
+                borrow: borrow_b,
+            },
+        )
+    }
+
+    /// Converts into a reference to the underlying data with the lifetime
+    /// of the cell that it was borrowed from.
+    ///
+    /// This is an associated function that needs to be used as
+    /// `AtomicRef::leak(...)`. A method would interfere with methods of the
+    /// same name on the contents of the `AtomicRef` used through `Deref`.
+    ///
+    /// Note that the immutable borrow is held for as long as the cell is
+    /// borrowed, which is usually the lifetime of the entire program.
+    #[inline]
+    pub fn leak(orig: AtomicRef<'b, T>) -> &'b T {
+        let value = orig.value;
+        core::mem::forget(orig.borrow);
+        // SAFETY: the borrow is never released, so nothing can subsequently
+        // mutably borrow the cell's contents.
+        unsafe { &*value.as_ptr() }
+    }
 }
 
 impl<'b, T: ?Sized> AtomicRefMut<'b, T> {
@@ -459,6 +798,52 @@ impl<'b, T: ?Sized> AtomicRefMut<'b, T> {
             marker: PhantomData,
         })
     }
+
+    /// Splits an `AtomicRefMut` into two `AtomicRefMut`s for different
+    /// components of the borrowed data.
+    #[inline]
+    pub fn map_split<U: ?Sized, V: ?Sized, F>(
+        mut orig: AtomicRefMut<'b, T>,
+        f: F,
+    ) -> (AtomicRefMut<'b, U>, AtomicRefMut<'b, V>)
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        let (a, b) = f(&mut *orig);
+        let (a, b) = (NonNull::from(a), NonNull::from(b));
+        let borrow_b = orig.borrow.clone();
+        (
+            AtomicRefMut {
+                value: a,
+                borrow: orig.borrow,
+                marker: PhantomData,
+            },
+            AtomicRefMut {
+                value: b,
+                borrow: borrow_b,
+                marker: PhantomData,
+            },
+        )
+    }
+
+    /// Converts into a mutable reference to the underlying data with the
+    /// lifetime of the cell that it was borrowed from.
+    ///
+    /// This is an associated function that needs to be used as
+    /// `AtomicRefMut::leak(...)`. A method would interfere with methods of
+    /// the same name on the contents of the `AtomicRefMut` used through
+    /// `Deref`.
+    ///
+    /// Note that the mutable borrow is held for as long as the cell is
+    /// borrowed, which is usually the lifetime of the entire program.
+    #[inline]
+    pub fn leak(orig: AtomicRefMut<'b, T>) -> &'b mut T {
+        let mut value = orig.value;
+        core::mem::forget(orig.borrow);
+        // SAFETY: the borrow is never released, so nothing can subsequently
+        // borrow the cell's contents.
+        unsafe { value.as_mut() }
+    }
 }
 
 /// A wrapper type for a mutably borrowed value from an `AtomicRefCell<T>`.
@@ -0,0 +1,43 @@
+use atomic_refcell::AtomicRefCell;
+
+#[test]
+fn test_replace() {
+    let cell = AtomicRefCell::new(5);
+    let old = cell.replace(6);
+    assert_eq!(old, 5);
+    assert_eq!(*cell.borrow(), 6);
+}
+
+#[test]
+fn test_replace_with() {
+    let cell = AtomicRefCell::new(5);
+    let old = cell.replace_with(|v| *v + 1);
+    assert_eq!(old, 5);
+    assert_eq!(*cell.borrow(), 6);
+}
+
+#[test]
+fn test_take() {
+    let cell = AtomicRefCell::new(5);
+    let old = cell.take();
+    assert_eq!(old, 5);
+    assert_eq!(*cell.borrow(), 0);
+}
+
+#[test]
+fn test_swap() {
+    let a = AtomicRefCell::new(1);
+    let b = AtomicRefCell::new(2);
+    a.swap(&b);
+    assert_eq!(*a.borrow(), 2);
+    assert_eq!(*b.borrow(), 1);
+}
+
+#[test]
+fn test_swap_self_is_noop() {
+    let a = AtomicRefCell::new(1);
+    // If this didn't short-circuit on pointer equality, it would panic
+    // trying to borrow_mut the same cell twice.
+    a.swap(&a);
+    assert_eq!(*a.borrow(), 1);
+}
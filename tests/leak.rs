@@ -0,0 +1,24 @@
+use atomic_refcell::{AtomicRef, AtomicRefCell, AtomicRefMut};
+
+#[test]
+fn test_atomic_ref_leak() {
+    let cell = AtomicRefCell::new(5);
+    let value = AtomicRef::leak(cell.borrow());
+    assert_eq!(*value, 5);
+    // The leaked borrow is never released, so further immutable borrows are
+    // still fine, but a mutable borrow must be rejected forever.
+    let _still_readable = cell.borrow();
+    assert!(cell.try_borrow_mut().is_err());
+}
+
+#[test]
+fn test_atomic_ref_mut_leak() {
+    let cell = AtomicRefCell::new(5);
+    let value = AtomicRefMut::leak(cell.borrow_mut());
+    *value += 1;
+    assert_eq!(*value, 6);
+    // The leaked mutable borrow is never released, so the cell must remain
+    // mutably borrowed forever.
+    assert!(cell.try_borrow().is_err());
+    assert!(cell.try_borrow_mut().is_err());
+}
@@ -0,0 +1,79 @@
+use atomic_refcell::{AtomicRef, AtomicRefCell, AtomicRefMut};
+
+#[test]
+fn test_map_split_ref() {
+    let cell = AtomicRefCell::new((1, 2));
+    let borrow = cell.borrow();
+    let (a, b) = AtomicRef::map_split(borrow, |t| (&t.0, &t.1));
+    assert_eq!(*a, 1);
+    assert_eq!(*b, 2);
+
+    // Both halves are still live immutable borrows of the same cell.
+    assert!(cell.try_borrow_mut().is_err());
+    drop(a);
+    assert!(cell.try_borrow_mut().is_err());
+    drop(b);
+    assert!(cell.try_borrow_mut().is_ok());
+}
+
+#[test]
+fn test_map_split_ref_mut_soundness() {
+    let cell = AtomicRefCell::new((1, 2));
+    let borrow = cell.borrow_mut();
+    let (a, b) = AtomicRefMut::map_split(borrow, |t| (&mut t.0, &mut t.1));
+    assert_eq!(*a, 1);
+    assert_eq!(*b, 2);
+
+    // Neither half alone should allow a fresh borrow of any kind.
+    assert!(cell.try_borrow().is_err());
+    assert!(cell.try_borrow_mut().is_err());
+
+    drop(a);
+    // `b` is still live, so the cell must still be reported as exclusively
+    // borrowed: dropping one split guard must not unconditionally clear the
+    // cell out from under the other.
+    assert!(cell.try_borrow().is_err());
+    assert!(cell.try_borrow_mut().is_err());
+
+    drop(b);
+    // Now that both halves are gone, the cell is free again.
+    assert!(cell.try_borrow().is_ok());
+    assert!(cell.try_borrow_mut().is_ok());
+}
+
+#[test]
+fn test_map_split_ref_mut_many_splits() {
+    // Repeatedly split off one element at a time, exercising the
+    // guard-count bookkeeping (and its `clone`/`check_overflow` path) well
+    // below the `MAX_GUARD_COUNT` abort threshold.
+    let cell = AtomicRefCell::new(vec![0i32; 8]);
+    let borrow = cell.borrow_mut();
+    let (mut a, mut rest) = AtomicRefMut::map_split(borrow, |t| {
+        let (head, tail) = t.split_at_mut(1);
+        (&mut head[0], tail)
+    });
+
+    let mut guards = Vec::new();
+    for _ in 0..6 {
+        let (head, tail) = AtomicRefMut::map_split(rest, |t| {
+            let (head, tail) = t.split_at_mut(1);
+            (&mut head[0], tail)
+        });
+        guards.push(head);
+        rest = tail;
+    }
+
+    *a = 1;
+    for (i, guard) in guards.iter_mut().enumerate() {
+        **guard = i as i32 + 2;
+    }
+    rest[0] = 8;
+
+    assert!(cell.try_borrow().is_err());
+
+    drop(a);
+    drop(guards);
+    drop(rest);
+
+    assert_eq!(*cell.borrow(), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+}
@@ -0,0 +1,56 @@
+//! Tests for the `std`-only surface: `Display` text naming the conflicting
+//! borrow's type and call site, the `std::error::Error` impls, and panic
+//! messages. Run with `--features std`; compiles to an empty test binary
+//! otherwise, since none of this is available without the feature.
+#![cfg(feature = "std")]
+
+use std::error::Error;
+
+use atomic_refcell::{AtomicRefCell, BorrowError, BorrowMutError};
+
+#[test]
+fn test_try_borrow_error_display() {
+    let cell = AtomicRefCell::new(5i32);
+    let _guard = cell.borrow_mut();
+    let err = cell.try_borrow().unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("already mutably borrowed"));
+    assert!(message.contains("i32"));
+    assert!(message.contains("was taken at"));
+    assert!(message.contains(file!()));
+}
+
+#[test]
+fn test_try_borrow_mut_error_display() {
+    let cell = AtomicRefCell::new(5i32);
+    let _guard = cell.borrow();
+    let err = cell.try_borrow_mut().unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("already borrowed"));
+    assert!(message.contains("i32"));
+    assert!(message.contains("was taken at"));
+    assert!(message.contains(file!()));
+}
+
+#[test]
+fn test_errors_implement_std_error() {
+    fn assert_error<E: Error>() {}
+    assert_error::<BorrowError>();
+    assert_error::<BorrowMutError>();
+}
+
+#[test]
+#[should_panic(expected = "the conflicting borrow of `i32` was taken at")]
+fn test_borrow_mut_panic_message_includes_location() {
+    let cell = AtomicRefCell::new(5i32);
+    let _guard = cell.borrow();
+    let _ = cell.borrow_mut();
+}
+
+#[test]
+#[should_panic(expected = "the conflicting borrow of `i32` was taken at")]
+fn test_borrow_panic_message_includes_location() {
+    let cell = AtomicRefCell::new(5i32);
+    let _guard = cell.borrow_mut();
+    let _ = cell.borrow();
+}